@@ -1,6 +1,8 @@
 use bitvec::prelude::*;
 use clap::Parser;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::io::{self, BufRead};
 use thiserror::Error;
 use wayland_client::{
     delegate_noop,
@@ -27,22 +29,41 @@ use river_status::zriver_seat_status_v1;
 use river_status::zriver_status_manager_v1;
 
 delegate_noop!(State: ignore zriver_status_manager_v1::ZriverStatusManagerV1);
-delegate_noop!(State: ignore wl_output::WlOutput);
 delegate_noop!(State: ignore wl_seat::WlSeat);
 
+/// Per-output bit of state, keyed by the `wl_registry` global name that
+/// introduced the `wl_output` so events can be routed back to the right
+/// entry regardless of dispatch order.
+#[derive(Debug, Default, Clone)]
+struct OutputState {
+    output: Option<wl_output::WlOutput>,
+    status: Option<zriver_output_status_v1::ZriverOutputStatusV1>,
+
+    name: Option<String>,
+    layout: Option<String>,
+    focused: Option<BitVec<u32>>,
+    urgent: Option<BitVec<u32>>,
+    occupied: Option<BitVec<u32>>,
+
+    width: Option<i32>,
+    height: Option<i32>,
+    refresh: Option<i32>,
+    scale: Option<i32>,
+    x: Option<i32>,
+    y: Option<i32>,
+    transform: Option<String>,
+}
+
 #[derive(Debug, Default, Clone)]
 struct State {
     status_manager: Option<zriver_status_manager_v1::ZriverStatusManagerV1>,
     seat: Option<wl_seat::WlSeat>,
-    output: Option<wl_output::WlOutput>,
+    outputs: HashMap<u32, OutputState>,
 
     changed: bool,
 
     title: Option<String>,
     mode: Option<String>,
-    layout: Option<String>,
-    focused: Option<BitVec<u32>>,
-    urgent: Option<BitVec<u32>>,
 }
 
 #[derive(Parser)]
@@ -55,15 +76,191 @@ struct Cli {
     /// Number of tags you want to track
     #[arg(short, long, default_value_t = 9, value_parser = clap::value_parser!(u8).range(1..=32))]
     tags: u8,
+
+    /// Output format: `json` for the structured default, `i3bar` to speak
+    /// the i3bar/Waybar click-aware protocol on stdout, or a template
+    /// string such as `{mode} | {layout} | {title}` rendered once per
+    /// output. `{{` and `}}` escape literal braces. There are no separate
+    /// per-field format flags; each field is substituted directly in this
+    /// one template, and tag fields (`focused`, `urgent`, `occupied`)
+    /// require an index, e.g. `{focused:3}` — without one the token is
+    /// left unexpanded.
+    #[arg(long, default_value = "json")]
+    format: String,
 }
 
+/// Current mode/scale/geometry of an output, mirroring the data other
+/// compositors' IPCs expose for output management.
 #[derive(Serialize, Debug, Default, Clone, PartialEq)]
-struct Metadata {
-    title: String,
-    mode: String,
+struct OutputInfo {
+    /// Physical pixel width of the current mode.
+    width: i32,
+    /// Physical pixel height of the current mode.
+    height: i32,
+    /// Refresh rate of the current mode, in Hz.
+    refresh: i32,
+    scale: i32,
+    /// Physical (compositor-space) x position from `wl_output.geometry`,
+    /// *not* the scaled logical position `zxdg_output_v1` would give.
+    x: i32,
+    /// Physical (compositor-space) y position from `wl_output.geometry`,
+    /// *not* the scaled logical position `zxdg_output_v1` would give.
+    y: i32,
+    transform: String,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+struct OutputMetadata {
+    output: String,
     layout: Option<String>,
     urgent: Vec<bool>,
     focused: Vec<bool>,
+    occupied: Vec<bool>,
+    info: OutputInfo,
+}
+
+#[derive(Serialize, Debug, Default, Clone, PartialEq)]
+struct Metadata {
+    title: String,
+    mode: String,
+    outputs: Vec<OutputMetadata>,
+}
+
+/// One i3bar/Waybar status block, per the protocol's `full_text`/`color`/
+/// `background` fields.
+#[derive(Serialize, Debug, Clone)]
+struct Block {
+    full_text: String,
+    name: String,
+    instance: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    background: Option<String>,
+}
+
+/// A click event as sent back by i3bar/Waybar on stdin. We don't yet have
+/// anything to dispatch it to, so `main` just drains and discards these.
+#[derive(Deserialize, Debug)]
+struct ClickEvent {
+    #[allow(dead_code)]
+    name: Option<String>,
+    #[allow(dead_code)]
+    instance: Option<String>,
+    #[allow(dead_code)]
+    button: Option<u8>,
+}
+
+/// ORs together the per-view `u32` tag bitmasks packed in a `view_tags`
+/// event's native-endian `wl_array` payload into a single occupied-tags mask.
+fn occupied_tags(tags: &[u8]) -> BitVec<u32> {
+    let mask = tags
+        .chunks_exact(4)
+        .map(|mask| u32::from_ne_bytes(mask.try_into().unwrap()))
+        .fold(0u32, |occupied, mask| occupied | mask);
+
+    mask.view_bits::<Lsb0>().to_bitvec()
+}
+
+/// Renders `metadata` as the i3bar block array for one `changed` batch: a
+/// mode block, then a layout block and one block per tracked tag for each
+/// output.
+fn i3bar_blocks(metadata: &Metadata, tags: u8) -> Vec<Block> {
+    let mut blocks = vec![Block {
+        full_text: metadata.mode.clone(),
+        name: "mode".to_owned(),
+        instance: "mode".to_owned(),
+        color: None,
+        background: None,
+    }];
+
+    for output in &metadata.outputs {
+        blocks.push(Block {
+            full_text: output.layout.clone().unwrap_or_default(),
+            name: "layout".to_owned(),
+            instance: output.output.clone(),
+            color: None,
+            background: None,
+        });
+
+        for tag in 0..tags as usize {
+            let urgent = output.urgent.get(tag).copied().unwrap_or(false);
+            let focused = output.focused.get(tag).copied().unwrap_or(false);
+            let occupied = output.occupied.get(tag).copied().unwrap_or(false);
+
+            blocks.push(Block {
+                full_text: (tag + 1).to_string(),
+                name: "tag".to_owned(),
+                instance: format!("{}:{}", output.output, tag + 1),
+                color: urgent.then(|| "#ff0000".to_owned()),
+                background: match (focused, occupied) {
+                    (true, _) => Some("#285577".to_owned()),
+                    (false, true) => Some("#5c5c5c".to_owned()),
+                    (false, false) => None,
+                },
+            });
+        }
+    }
+
+    blocks
+}
+
+/// Expands `{field}`/`{field:index}` placeholders in `template` against
+/// `title`, `mode` and one output's metadata, with `{{`/`}}` as the escape
+/// for a literal brace.
+fn render_template(template: &str, title: &str, mode: &str, output: &OutputMetadata) -> String {
+    let mut result = String::new();
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                result.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                result.push('}');
+            }
+            '{' => {
+                let token: String = chars.by_ref().take_while(|&c| c != '}').collect();
+                result.push_str(&expand_field(&token, title, mode, output));
+            }
+            c => result.push(c),
+        }
+    }
+
+    result
+}
+
+fn expand_field(token: &str, title: &str, mode: &str, output: &OutputMetadata) -> String {
+    let (field, index) = match token.split_once(':') {
+        Some((field, index)) => (field, index.parse::<usize>().ok()),
+        None => (token, None),
+    };
+
+    let tag = |tags: &[bool]| -> String {
+        index
+            .and_then(|i| i.checked_sub(1))
+            .and_then(|i| tags.get(i))
+            .copied()
+            .unwrap_or(false)
+            .to_string()
+    };
+
+    match field {
+        "title" => title.to_owned(),
+        "mode" => mode.to_owned(),
+        "output" => output.output.clone(),
+        "layout" => output.layout.clone().unwrap_or_default(),
+        // Tag fields require an index (`{focused:3}`); leave the token
+        // unexpanded rather than silently printing "false" when it's missing.
+        "focused" | "urgent" | "occupied" if index.is_none() => format!("{{{token}}}"),
+        "focused" => tag(&output.focused),
+        "urgent" => tag(&output.urgent),
+        "occupied" => tag(&output.occupied),
+        _ => String::new(),
+    }
 }
 
 #[derive(Error, Debug)]
@@ -74,20 +271,28 @@ enum Error {
     #[error("missing mode in state")]
     MissingMode,
 
-    #[error("missing urgent tags list")]
+    #[error("missing name for output in state")]
+    MissingOutputName,
+
+    #[error("missing urgent tags list for output")]
     MissingUrgent,
 
-    #[error("missing focused tags list")]
+    #[error("missing focused tags list for output")]
     MissingFocused,
+
+    #[error("missing occupied tags list for output")]
+    MissingOccupied,
+
+    #[error("missing mode/scale/geometry for output")]
+    MissingOutputInfo,
 }
 
-impl TryInto<Metadata> for State {
+impl TryInto<OutputMetadata> for OutputState {
     type Error = crate::Error;
 
-    fn try_into(self) -> Result<Metadata, Self::Error> {
-        Ok(Metadata {
-            title: self.title.ok_or_else(|| Error::MissingTitle)?,
-            mode: self.mode.ok_or_else(|| Error::MissingMode)?,
+    fn try_into(self) -> Result<OutputMetadata, Self::Error> {
+        Ok(OutputMetadata {
+            output: self.name.ok_or_else(|| Error::MissingOutputName)?,
             urgent: self
                 .urgent
                 .ok_or_else(|| Error::MissingUrgent)?
@@ -98,27 +303,131 @@ impl TryInto<Metadata> for State {
                 .ok_or_else(|| Error::MissingFocused)?
                 .into_iter()
                 .collect(),
+            occupied: self
+                .occupied
+                .ok_or_else(|| Error::MissingOccupied)?
+                .into_iter()
+                .collect(),
             layout: self.layout,
+            info: OutputInfo {
+                width: self.width.ok_or_else(|| Error::MissingOutputInfo)?,
+                height: self.height.ok_or_else(|| Error::MissingOutputInfo)?,
+                refresh: self.refresh.ok_or_else(|| Error::MissingOutputInfo)?,
+                scale: self.scale.ok_or_else(|| Error::MissingOutputInfo)?,
+                x: self.x.ok_or_else(|| Error::MissingOutputInfo)?,
+                y: self.y.ok_or_else(|| Error::MissingOutputInfo)?,
+                transform: self.transform.ok_or_else(|| Error::MissingOutputInfo)?,
+            },
+        })
+    }
+}
+
+impl TryInto<Metadata> for State {
+    type Error = crate::Error;
+
+    fn try_into(self) -> Result<Metadata, Self::Error> {
+        let mut outputs = self
+            .outputs
+            .into_values()
+            .map(TryInto::try_into)
+            .collect::<Result<Vec<OutputMetadata>, _>>()?;
+        outputs.sort_by(|a, b| a.output.cmp(&b.output));
+
+        Ok(Metadata {
+            title: self.title.ok_or_else(|| Error::MissingTitle)?,
+            mode: self.mode.ok_or_else(|| Error::MissingMode)?,
+            outputs,
         })
     }
 }
 
-impl Dispatch<zriver_output_status_v1::ZriverOutputStatusV1, ()> for State {
+impl Dispatch<wl_output::WlOutput, u32> for State {
+    fn event(
+        state: &mut Self,
+        _output: &wl_output::WlOutput,
+        event: wl_output::Event,
+        global_name: &u32,
+        _: &Connection,
+        _qh: &QueueHandle<State>,
+    ) {
+        use wl_output::Event as E;
+
+        let Some(output_state) = state.outputs.get_mut(global_name) else {
+            return;
+        };
+
+        match event {
+            E::Name { name } => output_state.name = Some(name),
+
+            E::Geometry { x, y, transform, .. } => {
+                output_state.x = Some(x);
+                output_state.y = Some(y);
+                output_state.transform = Some(
+                    match transform.into_result() {
+                        Ok(wl_output::Transform::Normal) => "normal",
+                        Ok(wl_output::Transform::_90) => "90",
+                        Ok(wl_output::Transform::_180) => "180",
+                        Ok(wl_output::Transform::_270) => "270",
+                        Ok(wl_output::Transform::Flipped) => "flipped",
+                        Ok(wl_output::Transform::Flipped90) => "flipped-90",
+                        Ok(wl_output::Transform::Flipped180) => "flipped-180",
+                        Ok(wl_output::Transform::Flipped270) => "flipped-270",
+                        Ok(_) | Err(_) => "unknown",
+                    }
+                    .to_owned(),
+                );
+            }
+
+            E::Mode {
+                flags,
+                width,
+                height,
+                refresh,
+            } => {
+                if let wayland_client::WEnum::Value(flags) = flags {
+                    if flags.contains(wl_output::Mode::Current) {
+                        output_state.width = Some(width);
+                        output_state.height = Some(height);
+                        // `refresh` is in millihertz; round to the nearest Hz.
+                        output_state.refresh = Some((refresh + 500) / 1000);
+                    }
+                }
+            }
+
+            E::Scale { factor } => output_state.scale = Some(factor),
+
+            _ => {}
+        }
+
+        state.changed = true;
+    }
+}
+
+impl Dispatch<zriver_output_status_v1::ZriverOutputStatusV1, u32> for State {
     fn event(
         state: &mut Self,
         _output_status: &zriver_output_status_v1::ZriverOutputStatusV1,
         event: zriver_output_status_v1::Event,
-        _: &(),
+        global_name: &u32,
         _: &Connection,
         _qh: &QueueHandle<State>,
     ) {
         use zriver_output_status_v1::Event as E;
+
+        let Some(output_state) = state.outputs.get_mut(global_name) else {
+            return;
+        };
+
         match event {
-            E::FocusedTags { tags } => state.focused = Some(tags.view_bits().to_bitvec()),
-            E::UrgentTags { tags } => state.urgent = Some(tags.view_bits().to_bitvec()),
+            E::FocusedTags { tags } => output_state.focused = Some(tags.view_bits().to_bitvec()),
+            E::UrgentTags { tags } => output_state.urgent = Some(tags.view_bits().to_bitvec()),
+
+            E::ViewTags { tags } => {
+                output_state.occupied = Some(occupied_tags(&tags));
+            }
 
-            E::LayoutName { ref name } => state.layout = Some(name.to_owned()),
-            E::LayoutNameClear => state.layout = None,
+            E::LayoutName { ref name } => output_state.layout = Some(name.to_owned()),
+            E::LayoutNameClear => output_state.layout = None,
             _ => {}
         }
 
@@ -163,13 +472,17 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
         {
             match interface.as_str() {
                 "wl_output" => {
-                    let output = registry.bind::<wl_output::WlOutput, _, _>(name, 4, qh, ());
+                    let output = registry.bind::<wl_output::WlOutput, _, _>(name, 4, qh, name);
+
+                    let mut output_state = OutputState::default();
 
                     if let Some(ref status_manager) = state.status_manager {
-                        status_manager.get_river_output_status(&output, qh, ());
+                        let status = status_manager.get_river_output_status(&output, qh, name);
+                        output_state.status = Some(status);
                     }
 
-                    state.output = output.into();
+                    output_state.output = Some(output);
+                    state.outputs.insert(name, output_state);
                 }
                 "wl_seat" => {
                     let seat = registry.bind::<wl_seat::WlSeat, _, _>(name, 4, qh, ());
@@ -190,6 +503,14 @@ impl Dispatch<wl_registry::WlRegistry, ()> for State {
                         status_manager.get_river_seat_status(seat, qh, ());
                     }
 
+                    for (output_name, output_state) in state.outputs.iter_mut() {
+                        if let Some(ref output) = output_state.output {
+                            let status =
+                                status_manager.get_river_output_status(output, qh, *output_name);
+                            output_state.status = Some(status);
+                        }
+                    }
+
                     state.status_manager = status_manager.into();
                 }
                 _ => {}
@@ -210,25 +531,69 @@ fn main() {
 
     display.get_registry(&qh, ());
 
-    let mut state = State { changed: true, ..State::default() };
+    let mut state = State {
+        changed: true,
+        ..State::default()
+    };
+
+    let mut i3bar_first = true;
+    if cli.format == "i3bar" {
+        println!("{{\"version\":1,\"click_events\":true}}");
+        println!("[");
+
+        std::thread::spawn(|| {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                let _ = serde_json::from_str::<ClickEvent>(&line);
+            }
+        });
+    }
 
     loop {
         while state.title.is_none()
             || state.mode.is_none()
-            || state.layout.is_none()
-            || state.focused.is_none()
-            || state.urgent.is_none()
+            || state.outputs.is_empty()
+            || state.outputs.values().any(|output| {
+                output.name.is_none()
+                    || output.focused.is_none()
+                    || output.urgent.is_none()
+                    || output.occupied.is_none()
+                    || output.width.is_none()
+                    || output.height.is_none()
+                    || output.refresh.is_none()
+                    || output.scale.is_none()
+                    || output.x.is_none()
+                    || output.y.is_none()
+                    || output.transform.is_none()
+            })
             || !state.changed
         {
             event_queue.blocking_dispatch(&mut state).unwrap();
         }
 
         let mut metadata: Metadata = state.clone().try_into().unwrap();
-        metadata.urgent.truncate(cli.tags.into());
-        metadata.focused.truncate(cli.tags.into());
+        for output in metadata.outputs.iter_mut() {
+            output.urgent.truncate(cli.tags.into());
+            output.focused.truncate(cli.tags.into());
+            output.occupied.truncate(cli.tags.into());
+        }
 
         if state.changed {
-            println!("{}", serde_json::to_string(&metadata).unwrap());
+            if cli.format == "json" {
+                println!("{}", serde_json::to_string(&metadata).unwrap());
+            } else if cli.format == "i3bar" {
+                let blocks = i3bar_blocks(&metadata, cli.tags);
+                let prefix = if i3bar_first { "" } else { "," };
+                println!("{prefix}{}", serde_json::to_string(&blocks).unwrap());
+                i3bar_first = false;
+            } else {
+                for output in &metadata.outputs {
+                    println!(
+                        "{}",
+                        render_template(&cli.format, &metadata.title, &metadata.mode, output)
+                    );
+                }
+            }
+
             state.changed = false;
         }
 
@@ -237,3 +602,127 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn occupied_tags_ors_every_view_mask() {
+        let tags: Vec<u8> = [0b001u32, 0b010u32, 0b100u32]
+            .iter()
+            .flat_map(|mask| mask.to_ne_bytes())
+            .collect();
+
+        let occupied = occupied_tags(&tags);
+
+        assert_eq!(occupied.count_ones(), 3);
+        assert!(occupied[0] && occupied[1] && occupied[2]);
+    }
+
+    #[test]
+    fn occupied_tags_empty_array_is_all_clear() {
+        let occupied = occupied_tags(&[]);
+
+        assert_eq!(occupied.count_ones(), 0);
+    }
+
+    fn sample_output() -> OutputMetadata {
+        OutputMetadata {
+            output: "eDP-1".to_owned(),
+            layout: Some("tile".to_owned()),
+            urgent: vec![false, false, true],
+            focused: vec![false, true, false],
+            occupied: vec![true, true, false],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn render_template_substitutes_known_fields() {
+        let output = sample_output();
+        let rendered = render_template("{mode} | {layout} | {title}", "focused", "normal", &output);
+
+        assert_eq!(rendered, "normal | tile | focused");
+    }
+
+    #[test]
+    fn render_template_escapes_double_braces() {
+        let output = sample_output();
+        let rendered = render_template("{{{title}}}", "title", "mode", &output);
+
+        assert_eq!(rendered, "{title}");
+    }
+
+    #[test]
+    fn render_template_indexes_tag_fields_one_based() {
+        let output = sample_output();
+
+        assert_eq!(render_template("{focused:2}", "t", "m", &output), "true");
+        assert_eq!(render_template("{urgent:3}", "t", "m", &output), "true");
+        assert_eq!(render_template("{occupied:3}", "t", "m", &output), "false");
+    }
+
+    #[test]
+    fn render_template_leaves_unindexed_tag_fields_unexpanded() {
+        let output = sample_output();
+
+        assert_eq!(render_template("{focused}", "t", "m", &output), "{focused}");
+    }
+
+    #[test]
+    fn render_template_out_of_range_index_is_false() {
+        let output = sample_output();
+
+        assert_eq!(render_template("{focused:99}", "t", "m", &output), "false");
+    }
+
+    #[test]
+    fn i3bar_blocks_emits_mode_layout_and_one_block_per_tag() {
+        let metadata = Metadata {
+            title: "ignored".to_owned(),
+            mode: "normal".to_owned(),
+            outputs: vec![sample_output()],
+        };
+
+        let blocks = i3bar_blocks(&metadata, 3);
+
+        // 1 mode block + 1 layout block + 3 tag blocks.
+        assert_eq!(blocks.len(), 5);
+        assert_eq!(blocks[0].name, "mode");
+        assert_eq!(blocks[0].full_text, "normal");
+        assert_eq!(blocks[1].name, "layout");
+        assert_eq!(blocks[1].full_text, "tile");
+
+        let tag_blocks = &blocks[2..];
+        assert_eq!(tag_blocks[0].instance, "eDP-1:1");
+        assert_eq!(tag_blocks[1].instance, "eDP-1:2");
+        assert_eq!(tag_blocks[2].instance, "eDP-1:3");
+        assert_eq!(tag_blocks[0].full_text, "1");
+        assert_eq!(tag_blocks[2].full_text, "3");
+    }
+
+    #[test]
+    fn i3bar_blocks_colors_urgent_and_backgrounds_focused_and_occupied() {
+        let metadata = Metadata {
+            title: "ignored".to_owned(),
+            mode: "normal".to_owned(),
+            outputs: vec![sample_output()],
+        };
+
+        let blocks = i3bar_blocks(&metadata, 3);
+        let tag_blocks = &blocks[2..];
+
+        // Tag 1: occupied, not focused, not urgent -> dim background, no color.
+        assert_eq!(tag_blocks[0].color, None);
+        assert_eq!(tag_blocks[0].background.as_deref(), Some("#5c5c5c"));
+
+        // Tag 2: focused (and occupied) -> focused background wins, no color.
+        assert_eq!(tag_blocks[1].color, None);
+        assert_eq!(tag_blocks[1].background.as_deref(), Some("#285577"));
+
+        // Tag 3: urgent, neither focused nor occupied -> red color, no background.
+        assert_eq!(tag_blocks[2].color.as_deref(), Some("#ff0000"));
+        assert_eq!(tag_blocks[2].background, None);
+    }
+}